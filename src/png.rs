@@ -4,20 +4,23 @@
 /// Loosely based on https://www.w3.org/TR/2003/REC-PNG-20031110/
 use std::{
     collections::VecDeque,
-    io::{self, Read},
+    io::{self, Read, Write},
 };
 
-use flate2::{read::ZlibDecoder, Crc};
+use flate2::{
+    read::ZlibDecoder, write::ZlibEncoder, Compression, Crc, Decompress, DecompressError,
+    FlushDecompress, Status,
+};
 
 const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
 
 const ZLIB_COMPRESSION_METHOD: u8 = 0;
 
 // PLTE is required in indexed, allowed in truecolor and truecolor alpha and forbidden in grayscale and grayscale alpha
-const _GRAYSCALE: u8 = 0;
+const GRAYSCALE: u8 = 0;
 const TRUECOLOR: u8 = 2;
-const _INDEXED_COLOR: u8 = 3;
-const _GRAYSCALE_ALPHA: u8 = 4;
+const INDEXED_COLOR: u8 = 3;
+const GRAYSCALE_ALPHA: u8 = 4;
 const TRUECOLOR_ALPHA: u8 = 6;
 
 const FILTER_NONE: u8 = 0;
@@ -50,11 +53,61 @@ fn recon_c(recon: &[u8], stride: u32, bytes_per_pixel: u32, r: u32, c: u32) -> u
     }
 }
 
+/// Unpacks a reconstructed scanline into one raw sample per channel per
+/// pixel, expanding sub-byte (1/2/4-bit) samples MSB-first within each byte
+/// and reading 16-bit samples as big-endian pairs. Samples are returned
+/// un-scaled, i.e. in the range `0..=2^bit_depth - 1`.
+fn unpack_samples(row: &[u8], width: u32, channels: u32, bit_depth: u8) -> Vec<u32> {
+    let count = (width * channels) as usize;
+
+    match bit_depth {
+        1 | 2 | 4 => {
+            let mask = (1u8 << bit_depth) - 1;
+
+            (0..count)
+                .map(|i| {
+                    let bit_pos = i * bit_depth as usize;
+                    let byte = row[bit_pos / 8];
+                    let shift = 8 - bit_depth as usize - (bit_pos % 8);
+                    ((byte >> shift) & mask) as u32
+                })
+                .collect()
+        }
+        8 => row[..count].iter().map(|&b| b as u32).collect(),
+        16 => (0..count)
+            .map(|i| u16::from_be_bytes([row[i * 2], row[i * 2 + 1]]) as u32)
+            .collect(),
+        _ => unreachable!("IHDR bit depth must be 1, 2, 4, 8 or 16"),
+    }
+}
+
+/// Scales a raw `bit_depth`-bit sample up to the full 8-bit range, e.g. a
+/// 1-bit sample of `1` becomes `255` and a 16-bit sample is scaled down as
+/// `value * 255 / 65535`, rounding down.
+fn scale_sample(value: u32, bit_depth: u8) -> u8 {
+    let max = (1u32 << bit_depth) - 1;
+    ((value * 255) / max) as u8
+}
+
+/// Unpacks a reconstructed scanline of indexed-colour samples into one
+/// palette index per pixel. The `as u8` truncation below is lossless because
+/// `Ihdr::from_data` only allows indexed-colour images a bit depth of 1, 2,
+/// 4 or 8 — without that check a `bit_depth` of 16 would silently wrap
+/// out-of-range indices instead of surfacing `PaletteIndexOutOfRange`.
+fn unpack_indices(row: &[u8], width: u32, bit_depth: u8) -> Vec<u8> {
+    unpack_samples(row, width, 1, bit_depth)
+        .into_iter()
+        .map(|v| v as u8)
+        .collect()
+}
+
 fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
-    let p = a + b - c;
-    let pa = p.abs_diff(a);
-    let pb = p.abs_diff(b);
-    let pc = p.abs_diff(c);
+    // The spec mandates this sum run in a wider signed type: a + b - c can be
+    // negative or exceed u8::MAX even though a, b, c themselves never do.
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = p.abs_diff(a as i16);
+    let pb = p.abs_diff(b as i16);
+    let pc = p.abs_diff(c as i16);
 
     if pa <= pb && pa <= pc {
         a
@@ -65,22 +118,181 @@ fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
     }
 }
 
-#[derive(Debug)]
-pub enum PixelType {
-    Rgb,
-    Rgba,
+/// Applies `filter_type` to one scanline of raw (unfiltered) bytes, the
+/// inverse of the per-byte reconstruction in `reconstruct_scanlines`. `recon_a/b/c` double as the
+/// raw-neighbour lookup here since `raw` already holds every preceding row.
+fn filter_row(raw: &[u8], stride: u32, bytes_per_pixel: u32, r: u32, filter_type: u8) -> Vec<u8> {
+    (0..stride)
+        .map(|c| {
+            let byte = raw[(r * stride + c) as usize];
+
+            match filter_type {
+                FILTER_NONE => byte,
+                FILTER_SUB => byte.wrapping_sub(recon_a(raw, stride, bytes_per_pixel, r, c)),
+                FILTER_UP => byte.wrapping_sub(recon_b(raw, stride, r, c)),
+                FILTER_AVG => {
+                    let avg = ((recon_a(raw, stride, bytes_per_pixel, r, c) as u16
+                        + recon_b(raw, stride, r, c) as u16)
+                        / 2) as u8;
+                    byte.wrapping_sub(avg)
+                }
+                FILTER_PAETH => byte.wrapping_sub(paeth_predictor(
+                    recon_a(raw, stride, bytes_per_pixel, r, c),
+                    recon_b(raw, stride, r, c),
+                    recon_c(raw, stride, bytes_per_pixel, r, c),
+                )),
+                _ => unreachable!("only the five PNG filter types are tried"),
+            }
+        })
+        .collect()
 }
 
-impl PixelType {
-    fn bytes(&self) -> u32 {
-        match self {
-            Self::Rgb => 3,
-            Self::Rgba => 4,
-        }
+/// Sum-of-absolute-differences heuristic (as used by libpng/oxipng): each
+/// filtered byte is read as a signed residual, with 128..255 wrapping back
+/// towards zero, and the filter with the smallest total tends to compress
+/// best under deflate.
+fn filter_heuristic_sum(row: &[u8]) -> u32 {
+    row.iter()
+        .map(|&b| {
+            let v = b as u32;
+            if v >= 128 {
+                256 - v
+            } else {
+                v
+            }
+        })
+        .sum()
+}
+
+/// Picks, for one scanline, the filter type minimising `filter_heuristic_sum`.
+fn choose_filter(raw: &[u8], stride: u32, bytes_per_pixel: u32, r: u32) -> (u8, Vec<u8>) {
+    [
+        FILTER_NONE,
+        FILTER_SUB,
+        FILTER_UP,
+        FILTER_AVG,
+        FILTER_PAETH,
+    ]
+    .into_iter()
+    .map(|filter_type| {
+        (
+            filter_type,
+            filter_row(raw, stride, bytes_per_pixel, r, filter_type),
+        )
+    })
+    .min_by_key(|(_, filtered)| filter_heuristic_sum(filtered))
+    .expect("the filter type list is non-empty")
+}
+
+/// Counts the distinct byte values in a filtered scanline. Fewer distinct
+/// values tends to mean a smaller deflate output, which makes this a useful
+/// alternative to [`filter_heuristic_sum`] for some images.
+fn distinct_byte_count(row: &[u8]) -> usize {
+    let mut seen = [false; 256];
+    for &b in row {
+        seen[b as usize] = true;
+    }
+    seen.iter().filter(|&&b| b).count()
+}
+
+/// Picks, for one scanline, the filter type minimising `distinct_byte_count`.
+fn choose_filter_entropy(raw: &[u8], stride: u32, bytes_per_pixel: u32, r: u32) -> (u8, Vec<u8>) {
+    [
+        FILTER_NONE,
+        FILTER_SUB,
+        FILTER_UP,
+        FILTER_AVG,
+        FILTER_PAETH,
+    ]
+    .into_iter()
+    .map(|filter_type| {
+        (
+            filter_type,
+            filter_row(raw, stride, bytes_per_pixel, r, filter_type),
+        )
+    })
+    .min_by_key(|(_, filtered)| distinct_byte_count(filtered))
+    .expect("the filter type list is non-empty")
+}
+
+/// A whole-image filtering strategy tried by [`Image::optimize_to`].
+#[derive(Debug, Clone, Copy)]
+enum FilterStrategy {
+    /// Every scanline uses the same fixed filter type.
+    Fixed(u8),
+    /// Per scanline, the minimum-sum-of-absolute-differences heuristic (as
+    /// used by `Image::write`).
+    MinSum,
+    /// Per scanline, the fewest-distinct-bytes heuristic.
+    Entropy,
+}
+
+/// Returns the whole-image filter strategies to trial at a given
+/// `optimize_to` level. Higher levels try more (and more expensive)
+/// strategies.
+fn filter_strategies_for_level(level: u8) -> Vec<FilterStrategy> {
+    let mut strategies = vec![FilterStrategy::MinSum];
+
+    if level >= 2 {
+        strategies.push(FilterStrategy::Fixed(FILTER_NONE));
+        strategies.push(FilterStrategy::Fixed(FILTER_SUB));
+        strategies.push(FilterStrategy::Fixed(FILTER_UP));
+        strategies.push(FilterStrategy::Fixed(FILTER_AVG));
+        strategies.push(FilterStrategy::Fixed(FILTER_PAETH));
+    }
+
+    if level >= 3 {
+        strategies.push(FilterStrategy::Entropy);
+    }
+
+    strategies
+}
+
+/// Maps an `optimize_to` level to a zlib compression effort.
+fn compression_for_level(level: u8) -> Compression {
+    if level >= 3 {
+        Compression::best()
+    } else if level >= 2 {
+        Compression::default()
+    } else {
+        Compression::fast()
     }
 }
 
+/// Filters every scanline of `raw` according to `strategy`, producing the
+/// filter-type-prefixed byte stream that gets deflated into an IDAT chunk.
+fn filter_image(
+    raw: &[u8],
+    stride: u32,
+    bytes_per_pixel: u32,
+    height: u32,
+    strategy: FilterStrategy,
+) -> Vec<u8> {
+    let mut filtered = Vec::with_capacity((height * (1 + stride)) as usize);
+
+    for r in 0..height {
+        let (filter_type, scanline) = match strategy {
+            FilterStrategy::Fixed(filter_type) => (
+                filter_type,
+                filter_row(raw, stride, bytes_per_pixel, r, filter_type),
+            ),
+            FilterStrategy::MinSum => choose_filter(raw, stride, bytes_per_pixel, r),
+            FilterStrategy::Entropy => choose_filter_entropy(raw, stride, bytes_per_pixel, r),
+        };
+        filtered.push(filter_type);
+        filtered.extend_from_slice(&scanline);
+    }
+
+    filtered
+}
+
 #[derive(Debug)]
+pub enum PixelType {
+    Rgb,
+    Rgba,
+}
+
+#[derive(Debug, Clone)]
 pub enum Pixel {
     Rgb([u8; 3]),
     Rgba([u8; 4]),
@@ -102,10 +314,16 @@ pub enum Error {
     InvalidStartingChunk,
     Unimplemented,
     InvalidIHDRLength,
+    InvalidBitDepth,
     InvalidPLTESize,
     UnsupportedCompressionMethod,
     InvalidFilterType,
     MismatchedCrc,
+    MissingPalette,
+    PaletteIndexOutOfRange,
+    Inflate(DecompressError),
+    UnexpectedEndOfImage,
+    InvalidTrnsSize,
 }
 
 impl From<io::Error> for Error {
@@ -114,10 +332,17 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<DecompressError> for Error {
+    fn from(err: DecompressError) -> Self {
+        Self::Inflate(err)
+    }
+}
+
 #[derive(Debug)]
 enum Chunk {
     Ihdr(Ihdr),
     Plte(Plte),
+    Trns(Vec<u8>),
     Idat(Vec<u8>),
     Iend,
 }
@@ -131,6 +356,9 @@ impl Chunk {
         match &type_bytes {
             b"IHDR" => Ok(Some(Self::Ihdr(Ihdr::from_data(&data)?))),
             b"PLTE" => Ok(Some(Self::Plte(Plte::from_data(&data)?))),
+            // tRNS's layout depends on the colour type carried by IHDR, so it
+            // is parsed later, once `Image::from_chunks` knows that.
+            b"tRNS" => Ok(Some(Self::Trns(data))),
             b"IDAT" => Ok(Some(Self::Idat(data))),
             b"IEND" => Ok(Some(Self::Iend)),
             _ => {
@@ -169,6 +397,19 @@ impl Chunk {
             Err(Error::MismatchedCrc)
         }
     }
+
+    fn write<W: Write>(out: &mut W, type_bytes: &[u8; 4], data: &[u8]) -> io::Result<()> {
+        out.write_all(&(data.len() as u32).to_be_bytes())?;
+        out.write_all(type_bytes)?;
+        out.write_all(data)?;
+
+        let mut hasher = Crc::new();
+        hasher.update(type_bytes);
+        hasher.update(data);
+        out.write_all(&hasher.sum().to_be_bytes())?;
+
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -196,6 +437,17 @@ impl Ihdr {
         let filter_method = data[11];
         let interlace_method = data[12];
 
+        let allowed_bit_depths: &[u8] = match colour_type {
+            GRAYSCALE => &[1, 2, 4, 8, 16],
+            TRUECOLOR | GRAYSCALE_ALPHA | TRUECOLOR_ALPHA => &[8, 16],
+            INDEXED_COLOR => &[1, 2, 4, 8],
+            _ => return Err(Error::Unimplemented),
+        };
+
+        if !allowed_bit_depths.contains(&bit_depth) {
+            return Err(Error::InvalidBitDepth);
+        }
+
         Ok(Self {
             width,
             height,
@@ -207,13 +459,40 @@ impl Ihdr {
         })
     }
 
-    fn pixel_type(&self) -> Result<PixelType, Error> {
+    /// A `tRNS` chunk promotes an otherwise alpha-less colour type to `Rgba`
+    /// so callers get correct compositing data.
+    fn pixel_type(&self, has_trns: bool) -> Result<PixelType, Error> {
         match self.colour_type {
-            TRUECOLOR => Ok(PixelType::Rgb),
-            TRUECOLOR_ALPHA => Ok(PixelType::Rgba),
+            GRAYSCALE | TRUECOLOR | INDEXED_COLOR if has_trns => Ok(PixelType::Rgba),
+            GRAYSCALE | TRUECOLOR | INDEXED_COLOR => Ok(PixelType::Rgb),
+            GRAYSCALE_ALPHA | TRUECOLOR_ALPHA => Ok(PixelType::Rgba),
             _ => Err(Error::Unimplemented),
         }
     }
+
+    /// Number of samples per pixel before any indexing/expansion, i.e. the
+    /// channel count of the raw scanline data (not of the decoded `Pixel`s).
+    fn channels(&self) -> u32 {
+        match self.colour_type {
+            GRAYSCALE => 1,
+            TRUECOLOR => 3,
+            INDEXED_COLOR => 1,
+            GRAYSCALE_ALPHA => 2,
+            TRUECOLOR_ALPHA => 4,
+            _ => 1,
+        }
+    }
+
+    /// Bytes per (possibly sub-byte) pixel used by the filter reconstruction,
+    /// i.e. `max(1, channels * bit_depth / 8)` per the PNG spec.
+    fn bytes_per_pixel(&self) -> u32 {
+        (self.channels() * self.bit_depth as u32).div_ceil(8)
+    }
+
+    /// Bytes per (unfiltered) scanline, i.e. `ceil(width * channels * bit_depth / 8)`.
+    fn stride(&self) -> u32 {
+        (self.width * self.channels() * self.bit_depth as u32).div_ceil(8)
+    }
 }
 
 #[derive(Debug)]
@@ -239,6 +518,269 @@ impl Plte {
     }
 }
 
+/// A parsed `tRNS` chunk. Its layout depends on the image's colour type:
+/// indexed images carry one alpha byte per palette entry (entries beyond it
+/// are fully opaque), while truecolor/grayscale images carry a single
+/// sample value that marks matching pixels as fully transparent.
+#[derive(Debug)]
+enum Trns {
+    Indexed(Vec<u8>),
+    Gray(u16),
+    Rgb(u16, u16, u16),
+}
+
+impl Trns {
+    fn from_data(data: &[u8], colour_type: u8) -> Result<Self, Error> {
+        match colour_type {
+            INDEXED_COLOR => Ok(Self::Indexed(data.to_vec())),
+            GRAYSCALE => {
+                if data.len() != 2 {
+                    return Err(Error::InvalidTrnsSize);
+                }
+
+                Ok(Self::Gray(u16::from_be_bytes([data[0], data[1]])))
+            }
+            TRUECOLOR => {
+                if data.len() != 6 {
+                    return Err(Error::InvalidTrnsSize);
+                }
+
+                Ok(Self::Rgb(
+                    u16::from_be_bytes([data[0], data[1]]),
+                    u16::from_be_bytes([data[2], data[3]]),
+                    u16::from_be_bytes([data[4], data[5]]),
+                ))
+            }
+            // tRNS is forbidden alongside colour types that already carry alpha.
+            _ => Err(Error::Unimplemented),
+        }
+    }
+}
+
+/// Reconstructs `height` filtered scanlines of `stride` bytes each, starting
+/// at `offset` into `data`. Filtering always resets at the first row, which
+/// is what makes this usable both for a whole (non-interlaced) image and for
+/// a single Adam7 pass. Returns the reconstructed bytes and the offset just
+/// past the last scanline consumed.
+fn reconstruct_scanlines(
+    data: &[u8],
+    offset: usize,
+    height: u32,
+    stride: u32,
+    bytes_per_pixel: u32,
+) -> Result<(Vec<u8>, usize), Error> {
+    let mut recon = Vec::with_capacity((height * stride) as usize);
+    let mut i = offset;
+
+    for r in 0..height {
+        if i + 1 + stride as usize > data.len() {
+            return Err(Error::UnexpectedEndOfImage);
+        }
+
+        let filter_type = data[i];
+        i += 1;
+
+        for c in 0..stride {
+            let byte = data[i];
+            i += 1;
+
+            let recon_byte = match filter_type {
+                FILTER_NONE => byte,
+                FILTER_SUB => byte.wrapping_add(recon_a(&recon, stride, bytes_per_pixel, r, c)),
+                FILTER_UP => byte.wrapping_add(recon_b(&recon, stride, r, c)),
+                FILTER_AVG => {
+                    let avg = ((recon_a(&recon, stride, bytes_per_pixel, r, c) as u16
+                        + recon_b(&recon, stride, r, c) as u16)
+                        / 2) as u8;
+                    byte.wrapping_add(avg)
+                }
+                FILTER_PAETH => byte.wrapping_add(paeth_predictor(
+                    recon_a(&recon, stride, bytes_per_pixel, r, c),
+                    recon_b(&recon, stride, r, c),
+                    recon_c(&recon, stride, bytes_per_pixel, r, c),
+                )),
+                _ => return Err(Error::InvalidFilterType),
+            };
+
+            recon.push(recon_byte);
+        }
+    }
+
+    Ok((recon, i))
+}
+
+/// Assembles reconstructed scanlines (`width` x `height`, possibly a single
+/// Adam7 pass rather than the full image) into decoded `Pixel`s.
+fn assemble_pixels(
+    ihdr: &Ihdr,
+    recon: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    plte: Option<&Plte>,
+    trns: Option<&Trns>,
+) -> Result<Vec<Vec<Pixel>>, Error> {
+    let colour_type = ihdr.colour_type;
+    let bit_depth = ihdr.bit_depth;
+    let channels = ihdr.channels();
+    let mut pixels = Vec::with_capacity(height as usize);
+
+    if colour_type == INDEXED_COLOR {
+        let plte = plte.expect("checked for MissingPalette before decoding");
+        let alphas = match trns {
+            Some(Trns::Indexed(alphas)) => Some(alphas),
+            _ => None,
+        };
+
+        for y in 0..height {
+            let row_start = (y * stride) as usize;
+            let row_bytes = &recon[row_start..row_start + stride as usize];
+
+            let mut row = Vec::with_capacity(width as usize);
+
+            for index in unpack_indices(row_bytes, width, bit_depth) {
+                let entry = plte
+                    .entries
+                    .get(index as usize)
+                    .ok_or(Error::PaletteIndexOutOfRange)?;
+
+                let pixel = match (entry, alphas) {
+                    (Pixel::Rgb([r, g, b]), Some(alphas)) => {
+                        let a = alphas.get(index as usize).copied().unwrap_or(255);
+                        Pixel::Rgba([*r, *g, *b, a])
+                    }
+                    (entry, _) => entry.clone(),
+                };
+
+                row.push(pixel);
+            }
+
+            pixels.push(row);
+        }
+    } else {
+        for y in 0..height {
+            let row_start = (y * stride) as usize;
+            let row_bytes = &recon[row_start..row_start + stride as usize];
+            let samples = unpack_samples(row_bytes, width, channels, bit_depth);
+
+            let mut row = Vec::with_capacity(width as usize);
+
+            for x in 0..width as usize {
+                let raw_sample = |c: usize| samples[x * channels as usize + c];
+                let sample = |c: usize| scale_sample(raw_sample(c), bit_depth);
+
+                let pixel = match colour_type {
+                    GRAYSCALE => {
+                        let gray = sample(0);
+
+                        match trns {
+                            Some(Trns::Gray(key)) if raw_sample(0) == *key as u32 => {
+                                Pixel::Rgba([gray, gray, gray, 0])
+                            }
+                            Some(Trns::Gray(_)) => Pixel::Rgba([gray, gray, gray, 255]),
+                            _ => Pixel::Rgb([gray, gray, gray]),
+                        }
+                    }
+                    TRUECOLOR => {
+                        let (r, g, b) = (sample(0), sample(1), sample(2));
+
+                        match trns {
+                            Some(Trns::Rgb(kr, kg, kb))
+                                if (raw_sample(0), raw_sample(1), raw_sample(2))
+                                    == (*kr as u32, *kg as u32, *kb as u32) =>
+                            {
+                                Pixel::Rgba([r, g, b, 0])
+                            }
+                            Some(Trns::Rgb(..)) => Pixel::Rgba([r, g, b, 255]),
+                            _ => Pixel::Rgb([r, g, b]),
+                        }
+                    }
+                    GRAYSCALE_ALPHA => {
+                        let gray = sample(0);
+                        Pixel::Rgba([gray, gray, gray, sample(1)])
+                    }
+                    TRUECOLOR_ALPHA => Pixel::Rgba([sample(0), sample(1), sample(2), sample(3)]),
+                    _ => unreachable!("pixel_type() already rejected other colour types"),
+                };
+
+                row.push(pixel);
+            }
+
+            pixels.push(row);
+        }
+    }
+
+    Ok(pixels)
+}
+
+// Adam7 pass geometry: starting offset and step per axis, indexed by pass 0..7.
+const ADAM7_X_START: [u32; 7] = [0, 4, 0, 2, 0, 1, 0];
+const ADAM7_Y_START: [u32; 7] = [0, 0, 4, 0, 2, 0, 1];
+const ADAM7_X_STEP: [u32; 7] = [8, 8, 4, 4, 2, 2, 1];
+const ADAM7_Y_STEP: [u32; 7] = [8, 8, 8, 4, 4, 2, 2];
+
+/// Number of samples an Adam7 pass covers along one axis, or 0 if the pass
+/// doesn't reach the image at all (e.g. a 1-pixel-wide image has no pass 1).
+fn adam7_pass_dimension(full: u32, start: u32, step: u32) -> u32 {
+    if full > start {
+        (full - start).div_ceil(step)
+    } else {
+        0
+    }
+}
+
+/// Decodes an Adam7-interlaced IDAT stream: the seven reduced passes are
+/// stored back to back, each filtered independently, and interleave into
+/// the final image by `final = start[pass] + index * step[pass]`.
+fn decode_adam7(
+    ihdr: &Ihdr,
+    idat_data: &[u8],
+    bytes_per_pixel: u32,
+    plte: Option<&Plte>,
+    trns: Option<&Trns>,
+) -> Result<Vec<Vec<Pixel>>, Error> {
+    let channels = ihdr.channels();
+    let mut grid: Vec<Vec<Option<Pixel>>> = (0..ihdr.height)
+        .map(|_| (0..ihdr.width).map(|_| None).collect())
+        .collect();
+
+    let mut offset = 0;
+
+    for pass in 0..7 {
+        let pass_w = adam7_pass_dimension(ihdr.width, ADAM7_X_START[pass], ADAM7_X_STEP[pass]);
+        let pass_h = adam7_pass_dimension(ihdr.height, ADAM7_Y_START[pass], ADAM7_Y_STEP[pass]);
+
+        if pass_w == 0 || pass_h == 0 {
+            continue;
+        }
+
+        let pass_stride = (pass_w * channels * ihdr.bit_depth as u32).div_ceil(8);
+        let (recon, next_offset) =
+            reconstruct_scanlines(idat_data, offset, pass_h, pass_stride, bytes_per_pixel)?;
+        offset = next_offset;
+
+        let pass_pixels = assemble_pixels(ihdr, &recon, pass_w, pass_h, pass_stride, plte, trns)?;
+
+        for (row, pixel_row) in pass_pixels.into_iter().enumerate() {
+            let y = (ADAM7_Y_START[pass] + row as u32 * ADAM7_Y_STEP[pass]) as usize;
+
+            for (col, pixel) in pixel_row.into_iter().enumerate() {
+                let x = (ADAM7_X_START[pass] + col as u32 * ADAM7_X_STEP[pass]) as usize;
+                grid[y][x] = Some(pixel);
+            }
+        }
+    }
+
+    Ok(grid
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|pixel| pixel.expect("Adam7 passes cover every pixel exactly once"))
+                .collect()
+        })
+        .collect())
+}
+
 #[derive(Debug)]
 pub struct Image {
     pub width: u32,
@@ -264,6 +806,16 @@ impl Image {
             None
         };
 
+        let trns = if matches!(chunks.front(), Some(Chunk::Trns(_))) {
+            if let Some(Chunk::Trns(data)) = chunks.pop_front() {
+                Some(Trns::from_data(&data, ihdr.colour_type)?)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
         let idat_data_compressed: Vec<u8> = chunks
             .into_iter()
             .filter_map(|chunk| {
@@ -276,10 +828,16 @@ impl Image {
             .flatten()
             .collect();
 
-        let pixel_type = ihdr.pixel_type()?;
-        let bytes_per_pixel = pixel_type.bytes();
+        let pixel_type = ihdr.pixel_type(trns.is_some())?;
 
-        let expected_idat_data_length = ihdr.height * (1 + ihdr.width * bytes_per_pixel);
+        if ihdr.colour_type == INDEXED_COLOR && plte.is_none() {
+            return Err(Error::MissingPalette);
+        }
+
+        let bytes_per_pixel = ihdr.bytes_per_pixel();
+        let stride = ihdr.stride();
+
+        let expected_idat_data_length = ihdr.height * (1 + stride);
         let mut idat_data = Vec::with_capacity(expected_idat_data_length as usize);
 
         if ihdr.compression_method == ZLIB_COMPRESSION_METHOD {
@@ -289,63 +847,20 @@ impl Image {
             return Err(Error::UnsupportedCompressionMethod);
         };
 
-        let stride = ihdr.width * bytes_per_pixel;
-        let mut recon = Vec::with_capacity((ihdr.height * stride) as usize);
-
-        let mut i = 0;
-        for r in 0..ihdr.height {
-            let filter_type = idat_data[i];
-            i += 1;
-
-            for c in 0..stride {
-                let byte = idat_data[i];
-                i += 1;
-
-                let recon_byte = match filter_type {
-                    FILTER_NONE => byte,
-                    FILTER_SUB => byte + recon_a(&recon, stride, bytes_per_pixel, r, c),
-                    FILTER_UP => byte + recon_b(&recon, stride, r, c),
-                    FILTER_AVG => {
-                        byte + (recon_a(&recon, stride, bytes_per_pixel, r, c)
-                            + recon_b(&recon, stride, r, c))
-                            / 2
-                    }
-                    FILTER_PAETH => {
-                        byte + paeth_predictor(
-                            recon_a(&recon, stride, bytes_per_pixel, r, c),
-                            recon_b(&recon, stride, r, c),
-                            recon_c(&recon, stride, bytes_per_pixel, r, c),
-                        )
-                    }
-                    _ => return Err(Error::InvalidFilterType),
-                };
-
-                recon.push(recon_byte);
-            }
-        }
-
-        let mut pixels = Vec::with_capacity(ihdr.height as usize);
-
-        let bytes_per_row = ihdr.width * bytes_per_pixel;
-
-        for y in 0..ihdr.height {
-            let mut row = Vec::with_capacity(ihdr.width as usize);
-
-            for x in 0..ihdr.width {
-                let idx = (y * bytes_per_row + x * bytes_per_pixel) as usize;
-
-                let pixel = match pixel_type {
-                    PixelType::Rgb => Pixel::Rgb([recon[idx], recon[idx + 1], recon[idx + 2]]),
-                    PixelType::Rgba => {
-                        Pixel::Rgba([recon[idx], recon[idx + 1], recon[idx + 2], recon[idx + 3]])
-                    }
-                };
-
-                row.push(pixel);
-            }
-
-            pixels.push(row);
-        }
+        let pixels = if ihdr.interlace_method == 1 {
+            decode_adam7(&ihdr, &idat_data, bytes_per_pixel, plte.as_ref(), trns.as_ref())?
+        } else {
+            let (recon, _) = reconstruct_scanlines(&idat_data, 0, ihdr.height, stride, bytes_per_pixel)?;
+            assemble_pixels(
+                &ihdr,
+                &recon,
+                ihdr.width,
+                ihdr.height,
+                stride,
+                plte.as_ref(),
+                trns.as_ref(),
+            )?
+        };
 
         Ok(Self {
             width: ihdr.width,
@@ -378,4 +893,730 @@ impl Image {
 
         Self::from_chunks(chunks)
     }
+
+    /// Encodes the image back to PNG: 8-bit, non-interlaced, with the filter
+    /// type of each scanline chosen via the minimum-sum-of-absolute-differences
+    /// heuristic before zlib-compressing the filtered data.
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(PNG_SIGNATURE)?;
+
+        let (colour_type, bytes_per_pixel) = self.pixel_layout();
+        Chunk::write(out, b"IHDR", &self.ihdr_chunk_data(colour_type))?;
+
+        let stride = self.width * bytes_per_pixel;
+        let raw = self.raw_pixel_bytes(bytes_per_pixel);
+        let filtered = filter_image(&raw, stride, bytes_per_pixel, self.height, FilterStrategy::MinSum);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&filtered)?;
+        let idat_data = encoder.finish()?;
+        Chunk::write(out, b"IDAT", &idat_data)?;
+
+        Chunk::write(out, b"IEND", &[])?;
+
+        Ok(())
+    }
+
+    /// Re-encodes the image, trial-encoding it under several whole-image
+    /// filter strategies (in the style of oxipng) and keeping whichever
+    /// compresses smallest. Higher `level`s try more strategies and spend
+    /// more effort on zlib compression, at the cost of encoding time:
+    /// - level 1: only the per-scanline minimum-sum-of-absolute-differences
+    ///   heuristic also used by `write`, with fast zlib compression.
+    /// - level 2: adds the five whole-image fixed-filter strategies, with
+    ///   default zlib compression.
+    /// - level 3+: adds a whole-image entropy (fewest distinct bytes per
+    ///   line) strategy, with maximum zlib compression.
+    pub fn optimize_to<W: Write>(&self, level: u8, out: &mut W) -> io::Result<()> {
+        out.write_all(PNG_SIGNATURE)?;
+
+        let (colour_type, bytes_per_pixel) = self.pixel_layout();
+        Chunk::write(out, b"IHDR", &self.ihdr_chunk_data(colour_type))?;
+
+        let stride = self.width * bytes_per_pixel;
+        let raw = self.raw_pixel_bytes(bytes_per_pixel);
+        let compression = compression_for_level(level);
+
+        let idat_data = filter_strategies_for_level(level)
+            .into_iter()
+            .map(|strategy| filter_image(&raw, stride, bytes_per_pixel, self.height, strategy))
+            .map(|filtered| {
+                let mut encoder = ZlibEncoder::new(Vec::new(), compression);
+                encoder.write_all(&filtered)?;
+                encoder.finish()
+            })
+            .collect::<io::Result<Vec<_>>>()?
+            .into_iter()
+            .min_by_key(Vec::len)
+            .expect("filter_strategies_for_level always returns at least one strategy");
+
+        Chunk::write(out, b"IDAT", &idat_data)?;
+        Chunk::write(out, b"IEND", &[])?;
+
+        Ok(())
+    }
+
+    fn pixel_layout(&self) -> (u8, u32) {
+        match self.pixel_type {
+            PixelType::Rgb => (TRUECOLOR, 3),
+            PixelType::Rgba => (TRUECOLOR_ALPHA, 4),
+        }
+    }
+
+    fn ihdr_chunk_data(&self, colour_type: u8) -> Vec<u8> {
+        let mut ihdr_data = Vec::with_capacity(13);
+        ihdr_data.extend_from_slice(&self.width.to_be_bytes());
+        ihdr_data.extend_from_slice(&self.height.to_be_bytes());
+        ihdr_data.push(8); // bit_depth
+        ihdr_data.push(colour_type);
+        ihdr_data.push(ZLIB_COMPRESSION_METHOD);
+        ihdr_data.push(0); // filter_method, only 0 exists
+        ihdr_data.push(0); // interlace_method, non-interlaced
+        ihdr_data
+    }
+
+    fn raw_pixel_bytes(&self, bytes_per_pixel: u32) -> Vec<u8> {
+        let mut raw = Vec::with_capacity((self.height * self.width * bytes_per_pixel) as usize);
+
+        for row in &self.pixels {
+            for pixel in row {
+                raw.extend_from_slice(pixel.raw());
+            }
+        }
+
+        raw
+    }
+}
+
+/// Metadata about a [`StreamingDecoder`]'s image, available as soon as its
+/// IHDR chunk has been parsed.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputInfo {
+    pub width: u32,
+    pub height: u32,
+    pub color_type: u8,
+    pub bit_depth: u8,
+    pub line_size: usize,
+}
+
+/// Reconstructs one scanline given the previous reconstructed scanline,
+/// without requiring the rest of the image in memory. This is the
+/// [`StreamingDecoder`]'s counterpart to `reconstruct_scanlines`, which
+/// instead indexes into a buffer holding every row decoded so far.
+fn reconstruct_row(
+    prev_row: &[u8],
+    filtered: &[u8],
+    bytes_per_pixel: u32,
+    filter_type: u8,
+) -> Result<Vec<u8>, Error> {
+    let bpp = bytes_per_pixel as usize;
+    let mut row = vec![0u8; filtered.len()];
+
+    for c in 0..filtered.len() {
+        let a = if c >= bpp { row[c - bpp] } else { 0 };
+        let b = prev_row[c];
+        let cc = if c >= bpp { prev_row[c - bpp] } else { 0 };
+
+        row[c] = match filter_type {
+            FILTER_NONE => filtered[c],
+            FILTER_SUB => filtered[c].wrapping_add(a),
+            FILTER_UP => filtered[c].wrapping_add(b),
+            FILTER_AVG => filtered[c].wrapping_add(((a as u16 + b as u16) / 2) as u8),
+            FILTER_PAETH => filtered[c].wrapping_add(paeth_predictor(a, b, cc)),
+            _ => return Err(Error::InvalidFilterType),
+        };
+    }
+
+    Ok(row)
+}
+
+/// A push-style PNG decoder: instead of `Image::read` collecting every chunk
+/// and inflating the whole IDAT stream upfront, bytes are fed in as they
+/// arrive (e.g. off the network) and fully reconstructed scanlines are handed
+/// to a callback as soon as they're available. Memory use stays bounded by
+/// two scanlines plus whatever of the compressed stream hasn't been fed yet,
+/// rather than growing with the whole image. Only non-interlaced images are
+/// supported; interlaced streams need several passes' worth of state and are
+/// better served by `Image::read`.
+pub struct StreamingDecoder {
+    buffer: Vec<u8>,
+    signature_seen: bool,
+    ihdr: Option<Ihdr>,
+    inflater: Decompress,
+    pending: Vec<u8>,
+    prev_row: Vec<u8>,
+    row_index: u32,
 }
+
+impl StreamingDecoder {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            signature_seen: false,
+            ihdr: None,
+            inflater: Decompress::new(true),
+            pending: Vec::new(),
+            prev_row: Vec::new(),
+            row_index: 0,
+        }
+    }
+
+    /// Feeds another chunk of raw PNG bytes into the decoder, calling
+    /// `on_row` with each scanline as soon as it is fully reconstructed.
+    /// Returns the image's `OutputInfo` the moment it becomes known, i.e.
+    /// the call during which the IHDR chunk was completed.
+    pub fn update(
+        &mut self,
+        data: &[u8],
+        mut on_row: impl FnMut(&[u8]),
+    ) -> Result<Option<OutputInfo>, Error> {
+        self.buffer.extend_from_slice(data);
+
+        if !self.signature_seen {
+            if self.buffer.len() < PNG_SIGNATURE.len() {
+                return Ok(None);
+            }
+
+            if self.buffer[..PNG_SIGNATURE.len()] != *PNG_SIGNATURE {
+                return Err(Error::InvalidSignature);
+            }
+
+            self.buffer.drain(..PNG_SIGNATURE.len());
+            self.signature_seen = true;
+        }
+
+        let mut info = None;
+
+        // A chunk needs at least its 4-byte length and 4-byte type before we
+        // even know how much more data to wait for.
+        while self.buffer.len() >= 8 {
+            let length = u32::from_be_bytes(self.buffer[0..4].try_into().unwrap()) as usize;
+            let total = 8 + length + 4;
+
+            if self.buffer.len() < total {
+                break;
+            }
+
+            let type_bytes: [u8; 4] = self.buffer[4..8].try_into().unwrap();
+            let chunk_data = self.buffer[8..8 + length].to_vec();
+            let crc = u32::from_be_bytes(self.buffer[8 + length..total].try_into().unwrap());
+
+            let mut hasher = Crc::new();
+            hasher.update(&type_bytes);
+            hasher.update(&chunk_data);
+
+            if crc != hasher.sum() {
+                return Err(Error::MismatchedCrc);
+            }
+
+            self.buffer.drain(..total);
+
+            match &type_bytes {
+                b"IHDR" => {
+                    let ihdr = Ihdr::from_data(&chunk_data)?;
+
+                    if ihdr.interlace_method != 0 {
+                        return Err(Error::Unimplemented);
+                    }
+
+                    let output_info = OutputInfo {
+                        width: ihdr.width,
+                        height: ihdr.height,
+                        color_type: ihdr.colour_type,
+                        bit_depth: ihdr.bit_depth,
+                        line_size: ihdr.stride() as usize,
+                    };
+
+                    self.prev_row = vec![0; output_info.line_size];
+                    self.ihdr = Some(ihdr);
+                    info = Some(output_info);
+                }
+                b"IDAT" => self.feed_idat(&chunk_data, &mut on_row)?,
+                _ => {}
+            }
+        }
+
+        Ok(info)
+    }
+
+    fn feed_idat(&mut self, data: &[u8], on_row: &mut impl FnMut(&[u8])) -> Result<(), Error> {
+        let ihdr = self.ihdr.as_ref().ok_or(Error::InvalidStartingChunk)?;
+        let height = ihdr.height;
+        let bytes_per_pixel = ihdr.bytes_per_pixel();
+        let stride = ihdr.stride() as usize;
+
+        let mut input = data;
+        let mut scratch = [0u8; 8192];
+
+        loop {
+            let in_before = self.inflater.total_in();
+            let out_before = self.inflater.total_out();
+
+            let status = self
+                .inflater
+                .decompress(input, &mut scratch, FlushDecompress::None)?;
+
+            let consumed = (self.inflater.total_in() - in_before) as usize;
+            let produced = (self.inflater.total_out() - out_before) as usize;
+
+            self.pending.extend_from_slice(&scratch[..produced]);
+            input = &input[consumed..];
+
+            while self.row_index < height && self.pending.len() > stride {
+                let filter_type = self.pending[0];
+                let filtered = self.pending[1..1 + stride].to_vec();
+
+                let row = reconstruct_row(&self.prev_row, &filtered, bytes_per_pixel, filter_type)?;
+                on_row(&row);
+
+                self.prev_row = row;
+                self.pending.drain(..1 + stride);
+                self.row_index += 1;
+            }
+
+            if matches!(status, Status::StreamEnd) {
+                if self.row_index < height {
+                    return Err(Error::UnexpectedEndOfImage);
+                }
+                break;
+            }
+
+            if consumed == 0 && produced == 0 {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for StreamingDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn chunk_bytes(tag: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        Chunk::write(&mut out, tag, data).unwrap();
+        out
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Assembles a minimal PNG byte stream from its IHDR fields plus
+    /// optional PLTE/tRNS chunk data and the raw (unfiltered-byte) scanline
+    /// stream, deflating it into a single IDAT chunk.
+    fn build_png(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        colour_type: u8,
+        interlace_method: u8,
+        plte: Option<&[u8]>,
+        trns: Option<&[u8]>,
+        raw_scanlines: &[u8],
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(PNG_SIGNATURE);
+
+        let mut ihdr_data = Vec::with_capacity(13);
+        ihdr_data.extend_from_slice(&width.to_be_bytes());
+        ihdr_data.extend_from_slice(&height.to_be_bytes());
+        ihdr_data.push(bit_depth);
+        ihdr_data.push(colour_type);
+        ihdr_data.push(ZLIB_COMPRESSION_METHOD);
+        ihdr_data.push(0);
+        ihdr_data.push(interlace_method);
+        out.extend_from_slice(&chunk_bytes(b"IHDR", &ihdr_data));
+
+        if let Some(plte) = plte {
+            out.extend_from_slice(&chunk_bytes(b"PLTE", plte));
+        }
+
+        if let Some(trns) = trns {
+            out.extend_from_slice(&chunk_bytes(b"tRNS", trns));
+        }
+
+        out.extend_from_slice(&chunk_bytes(b"IDAT", &deflate(raw_scanlines)));
+        out.extend_from_slice(&chunk_bytes(b"IEND", &[]));
+
+        out
+    }
+
+    // --- chunk0-1: indexed-color (PLTE) decoding ---
+
+    #[test]
+    fn decodes_indexed_color_via_palette() {
+        let palette = [255, 0, 0, 0, 255, 0]; // index 0 = red, index 1 = green
+        let raw = [0, 0, 1, 0, 1, 0]; // row0: [red, green], row1: [green, red]
+        let data = build_png(2, 2, 8, INDEXED_COLOR, 0, Some(&palette), None, &raw);
+
+        let img = Image::read(&mut Cursor::new(data)).unwrap();
+
+        assert!(matches!(img.pixel_type, PixelType::Rgb));
+        assert_eq!(img.pixels[0][0].raw(), [255, 0, 0]);
+        assert_eq!(img.pixels[0][1].raw(), [0, 255, 0]);
+        assert_eq!(img.pixels[1][0].raw(), [0, 255, 0]);
+        assert_eq!(img.pixels[1][1].raw(), [255, 0, 0]);
+    }
+
+    #[test]
+    fn indexed_color_without_palette_is_an_error() {
+        let raw = [0, 0, 0];
+        let data = build_png(3, 1, 8, INDEXED_COLOR, 0, None, None, &raw);
+
+        assert!(matches!(
+            Image::read(&mut Cursor::new(data)),
+            Err(Error::MissingPalette)
+        ));
+    }
+
+    #[test]
+    fn out_of_range_palette_index_is_an_error() {
+        let palette = [255, 0, 0, 0, 255, 0]; // only 2 entries
+        let raw = [0, 5]; // index 5 is out of range
+        let data = build_png(1, 1, 8, INDEXED_COLOR, 0, Some(&palette), None, &raw);
+
+        assert!(matches!(
+            Image::read(&mut Cursor::new(data)),
+            Err(Error::PaletteIndexOutOfRange)
+        ));
+    }
+
+    // --- chunk0-2: bit depths and grayscale colour types ---
+
+    #[test]
+    fn scale_sample_expands_to_full_8_bit_range() {
+        assert_eq!(scale_sample(0, 1), 0);
+        assert_eq!(scale_sample(1, 1), 255);
+        assert_eq!(scale_sample(15, 4), 255);
+        assert_eq!(scale_sample(0, 4), 0);
+        assert_eq!(scale_sample(65535, 16), 255);
+    }
+
+    #[test]
+    fn unpack_samples_reads_sub_byte_depths_msb_first() {
+        // 0b1011_0000, 1-bit samples: top 4 bits are 1, 0, 1, 1.
+        let row = [0b1011_0000];
+        assert_eq!(unpack_samples(&row, 4, 1, 1), vec![1, 0, 1, 1]);
+    }
+
+    #[test]
+    fn unpack_samples_reads_16_bit_big_endian() {
+        let row = [0x01, 0x23, 0x45, 0x67];
+        assert_eq!(unpack_samples(&row, 2, 1, 16), vec![0x0123, 0x4567]);
+    }
+
+    #[test]
+    fn decodes_low_bit_depth_grayscale() {
+        // 2x1, bit_depth=2: samples 3 and 1 packed into one byte (0b11_01_00_00).
+        let raw = [0, 0b1101_0000];
+        let data = build_png(2, 1, 2, GRAYSCALE, 0, None, None, &raw);
+
+        let img = Image::read(&mut Cursor::new(data)).unwrap();
+
+        assert!(matches!(img.pixel_type, PixelType::Rgb));
+        assert_eq!(img.pixels[0][0].raw(), [255, 255, 255]);
+        assert_eq!(img.pixels[0][1].raw(), [85, 85, 85]);
+    }
+
+    #[test]
+    fn decodes_16_bit_grayscale_alpha() {
+        // 1x1, bit_depth=16: gray=0xFFFF, alpha=0x0000.
+        let raw = [0, 0xFF, 0xFF, 0x00, 0x00];
+        let data = build_png(1, 1, 16, GRAYSCALE_ALPHA, 0, None, None, &raw);
+
+        let img = Image::read(&mut Cursor::new(data)).unwrap();
+
+        assert!(matches!(img.pixel_type, PixelType::Rgba));
+        assert_eq!(img.pixels[0][0].raw(), [255, 255, 255, 0]);
+    }
+
+    #[test]
+    fn invalid_bit_depth_is_rejected_instead_of_panicking() {
+        let raw = [0, 0];
+        let data = build_png(1, 1, 3, GRAYSCALE, 0, None, None, &raw);
+
+        assert!(matches!(
+            Image::read(&mut Cursor::new(data)),
+            Err(Error::InvalidBitDepth)
+        ));
+    }
+
+
+    // --- chunk0-3: Adam7 interlacing ---
+
+    #[test]
+    fn decodes_adam7_interlaced_image() {
+        // 2x2 truecolor, 8-bit. Only passes 0, 5 and 6 touch a 2x2 image:
+        // pass 0 covers (0,0), pass 5 covers (1,0), pass 6 covers row y=1.
+        let p00 = [10, 20, 30];
+        let p10 = [40, 50, 60];
+        let p01 = [70, 80, 90];
+        let p11 = [100, 110, 120];
+
+        let mut raw = Vec::new();
+        raw.push(FILTER_NONE);
+        raw.extend_from_slice(&p00);
+        raw.push(FILTER_NONE);
+        raw.extend_from_slice(&p10);
+        raw.push(FILTER_NONE);
+        raw.extend_from_slice(&p01);
+        raw.extend_from_slice(&p11);
+
+        let data = build_png(2, 2, 8, TRUECOLOR, 1, None, None, &raw);
+        let img = Image::read(&mut Cursor::new(data)).unwrap();
+
+        assert_eq!(img.pixels[0][0].raw(), p00);
+        assert_eq!(img.pixels[0][1].raw(), p10);
+        assert_eq!(img.pixels[1][0].raw(), p01);
+        assert_eq!(img.pixels[1][1].raw(), p11);
+    }
+
+    #[test]
+    fn adam7_pass_dimension_handles_short_axes() {
+        assert_eq!(adam7_pass_dimension(2, 0, 8), 1);
+        assert_eq!(adam7_pass_dimension(2, 4, 8), 0);
+        assert_eq!(adam7_pass_dimension(8, 0, 8), 1);
+        assert_eq!(adam7_pass_dimension(9, 0, 8), 2);
+    }
+
+
+    // --- chunk0-4: PNG encoder with adaptive per-scanline filtering ---
+
+    #[test]
+    fn write_then_read_round_trips_pixels() {
+        let img = Image {
+            width: 3,
+            height: 2,
+            pixel_type: PixelType::Rgb,
+            pixels: vec![
+                vec![
+                    Pixel::Rgb([0, 0, 0]),
+                    Pixel::Rgb([10, 10, 10]),
+                    Pixel::Rgb([255, 255, 255]),
+                ],
+                vec![
+                    Pixel::Rgb([1, 2, 3]),
+                    Pixel::Rgb([4, 5, 6]),
+                    Pixel::Rgb([7, 8, 9]),
+                ],
+            ],
+        };
+
+        let mut out = Vec::new();
+        img.write(&mut out).unwrap();
+
+        assert!(out.starts_with(PNG_SIGNATURE));
+
+        let decoded = Image::read(&mut Cursor::new(out)).unwrap();
+
+        assert_eq!(decoded.width, img.width);
+        assert_eq!(decoded.height, img.height);
+
+        for (decoded_row, original_row) in decoded.pixels.iter().zip(img.pixels.iter()) {
+            for (decoded_pixel, original_pixel) in decoded_row.iter().zip(original_row.iter()) {
+                assert_eq!(decoded_pixel.raw(), original_pixel.raw());
+            }
+        }
+    }
+
+    #[test]
+    fn choose_filter_picks_the_lowest_heuristic_sum() {
+        // An ascending-by-one row: Sub filtering makes every residual byte
+        // after the first equal to the step size. On the first row Paeth
+        // predicts from the left neighbour too and ties with Sub; the tie
+        // goes to Sub, the earlier of the two filter types.
+        let raw = [10u8, 11, 12, 13, 14, 15];
+        let stride = 6;
+        let (filter_type, _) = choose_filter(&raw, stride, 1, 0);
+        assert_eq!(filter_type, FILTER_SUB);
+    }
+
+
+    // --- chunk0-5: wrapping arithmetic in filter reconstruction and Paeth ---
+
+    #[test]
+    fn paeth_predictor_does_not_panic_on_naive_u8_overflow() {
+        // a + b would overflow u8 (255 + 255); the wide i16 sum must not panic.
+        assert_eq!(paeth_predictor(255, 255, 0), 255);
+        // a + b - c going negative must not panic either.
+        assert_eq!(paeth_predictor(0, 0, 255), 0);
+    }
+
+    #[test]
+    fn reconstruct_scanlines_wraps_sub_filter_additions() {
+        // One row, two 1-byte pixels, Sub filter: recon[1] is
+        // byte.wrapping_add(recon[0]), and 200 + 200 must wrap to 144 rather
+        // than panic.
+        let raw = [FILTER_SUB, 200, 200];
+        let (recon, _) = reconstruct_scanlines(&raw, 0, 1, 2, 1).unwrap();
+        assert_eq!(recon, vec![200, 144]);
+    }
+
+
+    // --- chunk0-6: streaming decoder and truncated-stream detection ---
+
+    #[test]
+    fn streaming_decoder_matches_full_buffer_decode_when_fed_in_small_chunks() {
+        let palette = [255, 0, 0, 0, 255, 0];
+        let raw = [0, 0, 1, 0, 1, 0];
+        let data = build_png(2, 2, 8, INDEXED_COLOR, 0, Some(&palette), None, &raw);
+
+        let expected = Image::read(&mut Cursor::new(data.clone())).unwrap();
+
+        let mut decoder = StreamingDecoder::new();
+        let mut rows: Vec<Vec<u8>> = Vec::new();
+        let mut info = None;
+
+        for byte in &data {
+            if let Some(i) = decoder.update(&[*byte], |row| rows.push(row.to_vec())).unwrap() {
+                info = Some(i);
+            }
+        }
+
+        let info = info.unwrap();
+        assert_eq!(info.width, expected.width);
+        assert_eq!(info.height, expected.height);
+        assert_eq!(rows.len(), expected.height as usize);
+    }
+
+    #[test]
+    fn truncated_idat_is_an_error_via_image_read() {
+        // IHDR claims 5 rows, but the deflated data only encodes 3.
+        let mut raw = Vec::new();
+        for _ in 0..3 {
+            raw.push(FILTER_NONE);
+            raw.extend_from_slice(&[1, 2, 3]);
+        }
+        let data = build_png(3, 5, 8, GRAYSCALE, 0, None, None, &raw);
+
+        assert!(matches!(
+            Image::read(&mut Cursor::new(data)),
+            Err(Error::UnexpectedEndOfImage)
+        ));
+    }
+
+    #[test]
+    fn truncated_idat_is_an_error_via_streaming_decoder() {
+        let mut raw = Vec::new();
+        for _ in 0..3 {
+            raw.push(FILTER_NONE);
+            raw.extend_from_slice(&[1, 2, 3]);
+        }
+        let data = build_png(3, 5, 8, GRAYSCALE, 0, None, None, &raw);
+
+        let mut decoder = StreamingDecoder::new();
+        let result = decoder.update(&data, |_row| {});
+
+        assert!(matches!(result, Err(Error::UnexpectedEndOfImage)));
+    }
+
+
+    // --- chunk0-7: tRNS chunk handling ---
+
+    #[test]
+    fn indexed_color_trns_promotes_to_rgba() {
+        let palette = [255, 0, 0, 0, 255, 0]; // red, green
+        let trns = [0, 255]; // red is fully transparent, green is opaque
+        let raw = [0, 0, 1];
+        let data = build_png(2, 1, 8, INDEXED_COLOR, 0, Some(&palette), Some(&trns), &raw);
+
+        let img = Image::read(&mut Cursor::new(data)).unwrap();
+
+        assert!(matches!(img.pixel_type, PixelType::Rgba));
+        assert_eq!(img.pixels[0][0].raw(), [255, 0, 0, 0]);
+        assert_eq!(img.pixels[0][1].raw(), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn truecolor_trns_marks_matching_pixels_transparent() {
+        // tRNS color-key: (0, 255, 0) is the transparent color.
+        let trns = [0, 0, 0, 255, 0, 0]; // r=0, g=255, b=0 as three 16-bit samples
+        let raw = [
+            0, 0, 255, 0, // pixel 0: matches the key -> transparent
+            255, 0, 0, // pixel 1: does not match -> opaque
+        ];
+        let data = build_png(2, 1, 8, TRUECOLOR, 0, None, Some(&trns), &raw);
+
+        let img = Image::read(&mut Cursor::new(data)).unwrap();
+
+        assert!(matches!(img.pixel_type, PixelType::Rgba));
+        assert_eq!(img.pixels[0][0].raw(), [0, 255, 0, 0]);
+        assert_eq!(img.pixels[0][1].raw(), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn colour_type_without_trns_stays_rgb() {
+        let raw = [0, 1, 2, 3];
+        let data = build_png(1, 1, 8, TRUECOLOR, 0, None, None, &raw);
+
+        let img = Image::read(&mut Cursor::new(data)).unwrap();
+
+        assert!(matches!(img.pixel_type, PixelType::Rgb));
+    }
+
+
+    // --- chunk0-8: lossless re-optimization across filter strategies ---
+
+    fn sample_image() -> Image {
+        Image {
+            width: 4,
+            height: 4,
+            pixel_type: PixelType::Rgb,
+            pixels: (0..4)
+                .map(|y| {
+                    (0..4)
+                        .map(|x| Pixel::Rgb([(x * 10) as u8, (y * 10) as u8, 128]))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn optimize_to_round_trips_at_every_level() {
+        let img = sample_image();
+
+        for level in 1..=3 {
+            let mut out = Vec::new();
+            img.optimize_to(level, &mut out).unwrap();
+
+            let decoded = Image::read(&mut Cursor::new(out)).unwrap();
+            assert_eq!(decoded.width, img.width);
+            assert_eq!(decoded.height, img.height);
+
+            for (decoded_row, original_row) in decoded.pixels.iter().zip(img.pixels.iter()) {
+                for (decoded_pixel, original_pixel) in decoded_row.iter().zip(original_row.iter())
+                {
+                    assert_eq!(decoded_pixel.raw(), original_pixel.raw());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn higher_levels_try_more_filter_strategies() {
+        assert_eq!(filter_strategies_for_level(1).len(), 1);
+        assert_eq!(filter_strategies_for_level(2).len(), 6);
+        assert_eq!(filter_strategies_for_level(3).len(), 7);
+    }
+
+    #[test]
+    fn distinct_byte_count_counts_unique_values_only() {
+        assert_eq!(distinct_byte_count(&[1, 1, 2, 3, 3, 3]), 3);
+        assert_eq!(distinct_byte_count(&[5, 5, 5]), 1);
+        assert_eq!(distinct_byte_count(&[]), 0);
+    }
+
+}
\ No newline at end of file